@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, Filter, Log, H256, U256},
+    utils::keccak256,
+};
+
+use crate::l1::{DepositFilter, L1Client};
+use crate::{Scheduler, SharedLedger};
+
+/// A rollup-account exit staged by `request_withdrawal`, waiting to be
+/// folded into the next batch's exit-commitment. That commitment is kept
+/// with the batch in the `Ledger`; it is not submitted to the Router, and
+/// the Router has no function to claim a withdrawal on L1.
+#[derive(Debug, Clone)]
+pub struct Withdrawal {
+    pub to: Address,
+    pub amount: U256,
+}
+
+pub type WithdrawalQueue = Arc<Mutex<Vec<Withdrawal>>>;
+
+pub fn init_withdrawal_queue() -> WithdrawalQueue {
+    Arc::new(Mutex::new(Vec::new()))
+}
+
+/// Commit to an ordered list of withdrawals the same way `batch_tx_commitment`
+/// commits to a batch's transactions: keccak256 of the concatenated
+/// `(to, amount)` pairs.
+pub fn exit_commitment(withdrawals: &[Withdrawal]) -> U256 {
+    let concatenated: Vec<u8> = withdrawals
+        .iter()
+        .flat_map(|withdrawal| {
+            let mut amount_bytes = [0u8; 32];
+            withdrawal.amount.to_big_endian(&mut amount_bytes);
+            [withdrawal.to.as_bytes().to_vec(), amount_bytes.to_vec()].concat()
+        })
+        .collect();
+    U256::from_big_endian(&keccak256(concatenated))
+}
+
+fn erc20_transfer_topic() -> H256 {
+    H256::from(keccak256("Transfer(address,address,uint256)"))
+}
+
+/// A log's identity for dedupe purposes: unique forever, so once a
+/// `Transfer` backs one `Deposit` it can never be picked again for another.
+type LogKey = (Option<H256>, Option<U256>);
+
+fn log_key(log: &Log) -> LogKey {
+    (log.transaction_hash, log.log_index)
+}
+
+/// Find the first `Transfer` log in `transfers` that pays `amount` to
+/// `router` and hasn't already been consumed by an earlier `Deposit` in
+/// this same pass, so the same transfer can't back two deposit credits.
+fn find_unconsumed_transfer(
+    transfers: &[Log],
+    router: Address,
+    amount: U256,
+    consumed: &HashSet<LogKey>,
+) -> Option<usize> {
+    transfers.iter().position(|log| {
+        !consumed.contains(&log_key(log))
+            && log.topics.get(2).copied().map(Address::from) == Some(router)
+            && U256::from_big_endian(&log.data) == amount
+    })
+}
+
+/// Poll the Router for `Deposit` events and, for each one, check that a
+/// matching ERC20/ETH `Transfer` into the Router landed in the very same
+/// block before crediting the recipient's rollup balance. Requiring both
+/// logs to agree is what stops a spoofed `Deposit` event (emitted with no
+/// backing transfer) from minting balance out of thin air.
+pub async fn watch_deposits(
+    l1: Arc<L1Client>,
+    ledger: SharedLedger,
+    scheduler: Scheduler,
+) -> anyhow::Result<()> {
+    let mut from_block = l1.client.get_block_number().await?;
+    let mut interval = tokio::time::interval(Duration::from_secs(5));
+    let transfer_topic = erc20_transfer_topic();
+
+    loop {
+        interval.tick().await;
+        let to_block = l1.client.get_block_number().await?;
+        if to_block < from_block {
+            continue;
+        }
+
+        let deposits = l1
+            .router
+            .event::<DepositFilter>()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query_with_meta()
+            .await?;
+
+        // Tracks, within this pass, which `Transfer` logs have already
+        // backed a `Deposit` credit, so two `Deposit` events for the same
+        // amount in the same block can't both be confirmed by the one
+        // genuine `Transfer` between them.
+        let mut consumed_transfers: HashSet<LogKey> = HashSet::new();
+
+        for (deposit, meta) in deposits {
+            let transfers = l1
+                .client
+                .get_logs(
+                    &Filter::new()
+                        .from_block(meta.block_number)
+                        .to_block(meta.block_number)
+                        .address(l1.router.address())
+                        .topic0(transfer_topic),
+                )
+                .await?;
+
+            // Check the Transfer's recipient against the Router's own L1
+            // address, not `deposit.rollup_recipient`: that field is an
+            // attacker-controlled L2 address carried inside the very
+            // `Deposit` event being validated, so checking it against
+            // itself would let anyone satisfy this cross-check with an
+            // unrelated Transfer of their own.
+            let index = match find_unconsumed_transfer(
+                &transfers,
+                l1.router.address(),
+                deposit.amount,
+                &consumed_transfers,
+            ) {
+                Some(index) => index,
+                None => {
+                    eprintln!(
+                        "ignoring Deposit event with no matching unconsumed Transfer in block {}: {deposit:?}",
+                        meta.block_number
+                    );
+                    continue;
+                }
+            };
+            consumed_transfers.insert(log_key(&transfers[index]));
+
+            ledger
+                .lock()
+                .unwrap()
+                .state
+                .credit(deposit.rollup_recipient, deposit.amount);
+            scheduler
+                .lock()
+                .unwrap()
+                .credit(deposit.rollup_recipient, deposit.amount);
+        }
+
+        from_block = to_block + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(byte: u8) -> Address {
+        Address::from_low_u64_be(byte as u64)
+    }
+
+    fn transfer_log(tx_hash: H256, log_index: u64, to: Address, amount: U256) -> Log {
+        let mut data = [0u8; 32];
+        amount.to_big_endian(&mut data);
+        Log {
+            transaction_hash: Some(tx_hash),
+            log_index: Some(log_index.into()),
+            topics: vec![H256::zero(), H256::zero(), H256::from(to)],
+            data: data.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exit_commitment_differs_for_different_withdrawal_sets() {
+        let a = vec![Withdrawal {
+            to: addr(1),
+            amount: U256::from(10),
+        }];
+        let b = vec![Withdrawal {
+            to: addr(1),
+            amount: U256::from(11),
+        }];
+        assert_ne!(exit_commitment(&a), exit_commitment(&b));
+    }
+
+    #[test]
+    fn exit_commitment_is_order_sensitive() {
+        let forward = vec![
+            Withdrawal {
+                to: addr(1),
+                amount: U256::from(10),
+            },
+            Withdrawal {
+                to: addr(2),
+                amount: U256::from(20),
+            },
+        ];
+        let reversed = vec![forward[1].clone(), forward[0].clone()];
+        assert_ne!(exit_commitment(&forward), exit_commitment(&reversed));
+    }
+
+    #[test]
+    fn find_unconsumed_transfer_matches_router_and_amount() {
+        let router = addr(0xaa);
+        let transfers = vec![transfer_log(H256::zero(), 0, router, U256::from(100))];
+        let consumed = HashSet::new();
+
+        let index = find_unconsumed_transfer(&transfers, router, U256::from(100), &consumed);
+        assert_eq!(index, Some(0));
+    }
+
+    #[test]
+    fn find_unconsumed_transfer_skips_already_consumed_logs() {
+        let router = addr(0xaa);
+        let tx_hash = H256::from_low_u64_be(1);
+        let transfers = vec![transfer_log(tx_hash, 0, router, U256::from(100))];
+
+        let mut consumed = HashSet::new();
+        consumed.insert(log_key(&transfers[0]));
+
+        // The one real Transfer was already spent backing an earlier Deposit:
+        // a second Deposit for the same amount must not find it again.
+        assert_eq!(
+            find_unconsumed_transfer(&transfers, router, U256::from(100), &consumed),
+            None
+        );
+    }
+
+    #[test]
+    fn find_unconsumed_transfer_ignores_wrong_recipient_or_amount() {
+        let router = addr(0xaa);
+        let transfers = vec![transfer_log(H256::zero(), 0, addr(0xbb), U256::from(100))];
+        let consumed = HashSet::new();
+
+        assert_eq!(
+            find_unconsumed_transfer(&transfers, router, U256::from(100), &consumed),
+            None
+        );
+        assert_eq!(
+            find_unconsumed_transfer(&transfers, addr(0xbb), U256::from(99), &consumed),
+            None
+        );
+    }
+}
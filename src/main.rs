@@ -23,52 +23,144 @@ use serde::{Deserialize, Serialize};
 use tokio::{task, time::interval};
 use tower_http::cors::{Any, CorsLayer};
 
+mod bridge;
+mod l1;
 mod node;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Tx {
+use node::{AccountScheduler, Ledger};
+
+/// A legacy transfer: no fee-market fields, gas price is whatever the
+/// network expects out of band.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LegacyTx {
+    from: Address,
+    to: Address,
+    nonce: types::U256,
+    value: types::U256,
+}
+
+/// An EIP-1559 fee-market transfer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Eip1559Tx {
     from: Address,
     to: Address,
     nonce: types::U256,
     value: types::U256,
+    max_fee_per_gas: types::U256,
+    max_priority_fee_per_gas: types::U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Tx {
+    Legacy(LegacyTx),
+    Eip1559(Eip1559Tx),
+}
+
+impl Tx {
+    fn sender(&self) -> Address {
+        match self {
+            Tx::Legacy(tx) => tx.from,
+            Tx::Eip1559(tx) => tx.from,
+        }
+    }
+
+    fn recipient(&self) -> Address {
+        match self {
+            Tx::Legacy(tx) => tx.to,
+            Tx::Eip1559(tx) => tx.to,
+        }
+    }
+
+    fn nonce(&self) -> types::U256 {
+        match self {
+            Tx::Legacy(tx) => tx.nonce,
+            Tx::Eip1559(tx) => tx.nonce,
+        }
+    }
+
+    fn value(&self) -> types::U256 {
+        match self {
+            Tx::Legacy(tx) => tx.value,
+            Tx::Eip1559(tx) => tx.value,
+        }
+    }
+
+    /// The transaction-type byte prefixed onto the hash preimage, so a
+    /// legacy and an EIP-1559 tx with otherwise-identical fields can never
+    /// hash to the same digest.
+    fn type_byte(&self) -> u8 {
+        match self {
+            Tx::Legacy(_) => 0x00,
+            Tx::Eip1559(_) => 0x02,
+        }
+    }
 }
 
 impl From<CLITx> for Tx {
     fn from(tx: CLITx) -> Self {
-        Self {
-            from: tx.from,
-            to: tx.to,
-            nonce: tx.nonce,
-            value: tx.value,
+        match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+            (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) => Tx::Eip1559(Eip1559Tx {
+                from: tx.from,
+                to: tx.to,
+                nonce: tx.nonce,
+                value: tx.value,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            }),
+            _ => Tx::Legacy(LegacyTx {
+                from: tx.from,
+                to: tx.to,
+                nonce: tx.nonce,
+                value: tx.value,
+            }),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct SignedTx {
     tx: Tx,
     signature: String,
 }
 
+/// A request to debit `amount` from `from`'s rollup balance and queue it
+/// for exit on L1. `nonce` is checked against its own counter in
+/// `AccountScheduler`, separate from the transfer nonce, so a signed
+/// withdrawal request can't be replayed to debit the same funds twice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WithdrawalRequest {
+    from: Address,
+    amount: types::U256,
+    nonce: types::U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SignedWithdrawal {
+    request: WithdrawalRequest,
+    signature: String,
+}
+
 impl From<CLITx> for SignedTx {
     fn from(tx: CLITx) -> Self {
+        let signature = tx.signature.clone().unwrap();
         Self {
-            tx: Tx {
-                from: tx.from,
-                to: tx.to,
-                nonce: tx.nonce,
-                value: tx.value,
-            },
-            signature: tx.signature.unwrap(),
+            tx: tx.into(),
+            signature,
         }
     }
 }
 
 type Db = Arc<Mutex<Vec<SignedTx>>>;
+type SharedLedger = Arc<Mutex<Ledger>>;
+type Scheduler = Arc<Mutex<node::AccountScheduler>>;
 
 const DB_PATH: &str = "./db";
 const SOCKET_ADDRESS: &str = "127.0.0.1:38171";
 const SERVER_ADDRESS: &str = "http://localhost:38171";
+const L1_RPC_URL: &str = "http://localhost:8545";
+const SEQUENCER_PRIVATE_KEY: &str =
+    "0x0000000000000000000000000000000000000000000000000000000000000001";
 
 #[derive(Debug, Parser)]
 #[clap(name = "trollup sequencer", version = env!("CARGO_PKG_VERSION"))]
@@ -127,6 +219,18 @@ pub struct CLITx {
         default_value = "0"
     )]
     pub nonce: ethers::types::U256,
+    #[clap(
+        long,
+        value_name = "MAX_FEE_PER_GAS",
+        help = "The max total fee per gas (in wei) the sender will pay. Together with --max-priority-fee-per-gas, submits an EIP-1559 transaction instead of a legacy one."
+    )]
+    pub max_fee_per_gas: Option<ethers::types::U256>,
+    #[clap(
+        long,
+        value_name = "MAX_PRIORITY_FEE_PER_GAS",
+        help = "The max priority fee per gas (in wei), i.e. the tip, the sender will pay."
+    )]
+    pub max_priority_fee_per_gas: Option<ethers::types::U256>,
     #[clap(
         long,
         short = 's',
@@ -140,17 +244,82 @@ pub struct CLITx {
 async fn run_node() -> anyhow::Result<()> {
     let db_path = Path::new(DB_PATH);
     let db = init_db(db_path);
-    let rpc = init_rpc(db.clone()).await.unwrap();
-    //let l1 = init_l1(db.clone());
+    let ledger: SharedLedger = Arc::new(Mutex::new(Ledger::new()));
+    let scheduler: Scheduler = Arc::new(Mutex::new(AccountScheduler::new()));
+    let withdrawals = bridge::init_withdrawal_queue();
+    let rpc = init_rpc(
+        db.clone(),
+        ledger.clone(),
+        scheduler.clone(),
+        withdrawals.clone(),
+    )
+    .await
+    .unwrap();
+    let l1 = Arc::new(l1::init_l1(L1_RPC_URL, SEQUENCER_PRIVATE_KEY.parse()?).await?);
+
+    task::spawn(bridge::watch_deposits(
+        l1.clone(),
+        ledger.clone(),
+        scheduler.clone(),
+    ));
 
     task::spawn(async move {
         let mut interval = interval(Duration::from_millis(1000 * 5));
 
         loop {
             interval.tick().await;
-            let mut db = db.lock().unwrap();
-            println!("submit transactions {:#?}", db);
-            db.drain(..);
+            let txs: Vec<SignedTx> = db.lock().unwrap().drain(..).collect();
+            let staged_withdrawals: Vec<_> = withdrawals.lock().unwrap().drain(..).collect();
+            if txs.is_empty() && staged_withdrawals.is_empty() {
+                continue;
+            }
+
+            let len = txs.len();
+            let commitment = batch_tx_commitment(&txs);
+            let (pre_root, post_root) = {
+                let mut ledger = ledger.lock().unwrap();
+                let batch = ledger.commit_batch(txs, &staged_withdrawals);
+
+                // Mirror each transfer's credit into the scheduler too, the
+                // same way a bridge deposit does, so an account that only
+                // ever receives rollup-internal transfers isn't stuck
+                // showing balance 0 there and rejected on its own
+                // subsequent submissions.
+                let mut scheduler = scheduler.lock().unwrap();
+                for signed in &batch.txs {
+                    scheduler.credit(signed.tx.recipient(), signed.tx.value());
+                }
+
+                (batch.pre_root, batch.post_root)
+            };
+
+            let settlement = async {
+                let pending = l1
+                    .router
+                    .update_state(
+                        u256_to_bytes32(pre_root),
+                        u256_to_bytes32(post_root),
+                        commitment,
+                    )
+                    .send()
+                    .await?;
+                pending.await
+            }
+            .await;
+
+            match settlement {
+                Ok(receipt) => println!(
+                    "committed batch of {} tx(s): {:#x} -> {:#x}, settled on L1: {:?}",
+                    len,
+                    pre_root,
+                    post_root,
+                    receipt.map(|r| r.transaction_hash)
+                ),
+                Err(err) => eprintln!(
+                    "committed batch of {} tx(s): {:#x} -> {:#x}, but L1 settlement failed: {err}",
+                    len, pre_root, post_root
+                ),
+            }
         }
     });
 
@@ -188,22 +357,47 @@ async fn run_node() -> anyhow::Result<()> {
 
 fn hash_tx(sig_args: &Tx) -> ethers::types::TxHash {
     let mut value_bytes = vec![0; 32];
-    sig_args.value.to_big_endian(&mut value_bytes);
+    sig_args.value().to_big_endian(&mut value_bytes);
 
     let mut nonce_bytes = vec![0; 32];
-    sig_args.nonce.to_big_endian(&mut nonce_bytes);
-
-    let msg = [
-        sig_args.from.as_fixed_bytes().to_vec(),
-        sig_args.to.as_fixed_bytes().to_vec(),
-        value_bytes,
-        nonce_bytes,
-    ]
-    .concat();
+    sig_args.nonce().to_big_endian(&mut nonce_bytes);
+
+    let mut msg = vec![sig_args.type_byte()];
+    msg.extend(sig_args.sender().as_fixed_bytes());
+    msg.extend(sig_args.recipient().as_fixed_bytes());
+    msg.extend(value_bytes);
+    msg.extend(nonce_bytes);
+
+    if let Tx::Eip1559(tx) = sig_args {
+        let mut max_fee_bytes = [0u8; 32];
+        tx.max_fee_per_gas.to_big_endian(&mut max_fee_bytes);
+        let mut priority_fee_bytes = [0u8; 32];
+        tx.max_priority_fee_per_gas
+            .to_big_endian(&mut priority_fee_bytes);
+        msg.extend(max_fee_bytes);
+        msg.extend(priority_fee_bytes);
+    }
 
     types::TxHash::from(keccak256(msg))
 }
 
+fn u256_to_bytes32(value: types::U256) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    value.to_big_endian(&mut buf);
+    buf
+}
+
+/// A commitment to a batch's ordered transactions, submitted to the Router
+/// alongside the pre/post state roots so withdrawals and disputes can
+/// later reference exactly which txs produced a given root.
+fn batch_tx_commitment(txs: &[SignedTx]) -> types::Bytes {
+    let concatenated: Vec<u8> = txs
+        .iter()
+        .flat_map(|signed| hash_tx(&signed.tx).as_bytes().to_vec())
+        .collect();
+    types::Bytes::from(keccak256(concatenated).to_vec())
+}
+
 async fn sign(sig_args: CLITx) -> anyhow::Result<types::Signature> {
     let wallet: LocalWallet = SecretKey::from_be_bytes(sig_args.private_key.as_bytes())
         .expect("invalid private key")
@@ -218,7 +412,35 @@ async fn sign(sig_args: CLITx) -> anyhow::Result<types::Signature> {
 fn verify_tx_signature(signed_tx: &SignedTx) -> anyhow::Result<()> {
     let hash = hash_tx(&signed_tx.tx).as_fixed_bytes().to_vec();
     let decoded = signed_tx.signature.parse::<types::Signature>()?;
-    decoded.verify(hash, signed_tx.tx.from)?;
+    decoded.verify(hash, signed_tx.tx.sender())?;
+
+    Ok(())
+}
+
+/// Hash a withdrawal request with a tag byte distinct from any `Tx::type_byte`,
+/// so a withdrawal signature can never be replayed as (or confused with) a
+/// transfer signature over the same bytes.
+const WITHDRAWAL_TAG: u8 = 0xff;
+
+fn hash_withdrawal(request: &WithdrawalRequest) -> ethers::types::TxHash {
+    let mut amount_bytes = vec![0; 32];
+    request.amount.to_big_endian(&mut amount_bytes);
+
+    let mut nonce_bytes = vec![0; 32];
+    request.nonce.to_big_endian(&mut nonce_bytes);
+
+    let mut msg = vec![WITHDRAWAL_TAG];
+    msg.extend(request.from.as_fixed_bytes());
+    msg.extend(amount_bytes);
+    msg.extend(nonce_bytes);
+
+    types::TxHash::from(keccak256(msg))
+}
+
+fn verify_withdrawal_signature(signed: &SignedWithdrawal) -> anyhow::Result<()> {
+    let hash = hash_withdrawal(&signed.request).as_fixed_bytes().to_vec();
+    let decoded = signed.signature.parse::<types::Signature>()?;
+    decoded.verify(hash, signed.request.from)?;
 
     Ok(())
 }
@@ -262,11 +484,12 @@ fn init_db(path: &Path) -> Db {
     Arc::new(Mutex::new(vec![]))
 }
 
-fn init_l1(db: Db) -> Provider<Http> {
-    Provider::<Http>::try_from("https://mainnet.infura.io/v3/YOUR_API_KEY").unwrap()
-}
-
-async fn init_rpc(db: Db) -> anyhow::Result<ServerHandle> {
+async fn init_rpc(
+    db: Db,
+    ledger: SharedLedger,
+    scheduler: Scheduler,
+    withdrawals: bridge::WithdrawalQueue,
+) -> anyhow::Result<ServerHandle> {
     let cors = CorsLayer::new()
         // Allow `POST` when accessing the resource
         .allow_methods([Method::POST])
@@ -283,19 +506,110 @@ async fn init_rpc(db: Db) -> anyhow::Result<ServerHandle> {
 
     println!("{}", server.local_addr().unwrap());
 
+    let withdrawal_scheduler = scheduler.clone();
+    let withdrawal_ledger = ledger.clone();
+
     let mut module = RpcModule::new(());
     module.register_method("submit_transaction", move |params, _| {
         println!("received transaction! {:?}", params);
         let tx: SignedTx = params.parse()?;
 
         verify_tx_signature(&tx)?;
+        scheduler.lock().unwrap().admit(&tx.tx)?;
 
         let mut db = db.lock().unwrap();
         db.push(tx);
         Ok(())
     })?;
 
+    module.register_method("get_state_root", move |_, _| {
+        Ok::<String, jsonrpsee::core::Error>(format!("{:#x}", ledger.lock().unwrap().state_root()))
+    })?;
+
+    module.register_method("request_withdrawal", move |params, _| {
+        let signed: SignedWithdrawal = params.parse()?;
+        verify_withdrawal_signature(&signed)?;
+
+        let WithdrawalRequest { from, amount, nonce } = signed.request;
+        withdrawal_scheduler
+            .lock()
+            .unwrap()
+            .debit(from, amount, nonce)?;
+        withdrawal_ledger.lock().unwrap().state.debit(from, amount);
+        withdrawals.lock().unwrap().push(bridge::Withdrawal {
+            to: from,
+            amount,
+        });
+
+        Ok(())
+    })?;
+
     let handle = server.start(module)?;
 
     Ok(handle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn wallet() -> LocalWallet {
+        SecretKey::from_be_bytes(SEQUENCER_PRIVATE_KEY.parse::<types::H256>().unwrap().as_bytes())
+            .unwrap()
+            .into()
+    }
+
+    fn request(nonce: types::U256) -> WithdrawalRequest {
+        WithdrawalRequest {
+            from: Address::from_low_u64_be(1),
+            amount: types::U256::from(100),
+            nonce,
+        }
+    }
+
+    #[test]
+    fn hash_withdrawal_is_sensitive_to_every_field() {
+        let base = request(types::U256::zero());
+        let different_nonce = request(types::U256::one());
+        let mut different_amount = request(types::U256::zero());
+        different_amount.amount = types::U256::from(101);
+
+        assert_ne!(hash_withdrawal(&base), hash_withdrawal(&different_nonce));
+        assert_ne!(hash_withdrawal(&base), hash_withdrawal(&different_amount));
+    }
+
+    #[tokio::test]
+    async fn verify_withdrawal_signature_accepts_a_genuine_signature() {
+        let wallet = wallet().await;
+        let mut req = request(types::U256::zero());
+        req.from = wallet.address();
+
+        let hash = hash_withdrawal(&req).as_fixed_bytes().to_vec();
+        let signature = wallet.sign_message(hash).await.unwrap();
+
+        let signed = SignedWithdrawal {
+            request: req,
+            signature: signature.to_string(),
+        };
+        verify_withdrawal_signature(&signed).unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_withdrawal_signature_rejects_a_tampered_amount() {
+        let wallet = wallet().await;
+        let mut req = request(types::U256::zero());
+        req.from = wallet.address();
+
+        let hash = hash_withdrawal(&req).as_fixed_bytes().to_vec();
+        let signature = wallet.sign_message(hash).await.unwrap();
+
+        // Tamper with the amount after signing, same as an attacker replaying
+        // someone else's withdrawal signature over a bigger payout.
+        req.amount = types::U256::from(1_000_000);
+        let signed = SignedWithdrawal {
+            request: req,
+            signature: signature.to_string(),
+        };
+        assert!(verify_withdrawal_signature(&signed).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use ethers::types::{Address, U256};
+use jsonrpsee::core::Error as RpcError;
+use jsonrpsee::types::error::{CallError, ErrorObject};
+use trollup_sequencer::state::StateTree;
+
+use crate::bridge::Withdrawal;
+use crate::{SignedTx, Tx};
+
+/// A single committed batch: the state transition it produced, the ordered
+/// transactions that produced it, and a commitment to the withdrawals that
+/// exit alongside it. `run_node` settles the roots and a commitment to the
+/// transactions on L1; the exit-commitment is kept here only and is not sent.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub pre_root: U256,
+    pub post_root: U256,
+    pub txs: Vec<SignedTx>,
+    pub exit_commitment: U256,
+}
+
+/// The sequencer's view of the world: the live account state tree plus the
+/// log of batches committed against it.
+#[derive(Default)]
+pub struct Ledger {
+    pub state: StateTree,
+    batches: Vec<Batch>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state_root(&self) -> U256 {
+        self.state.root()
+    }
+
+    /// Apply `txs` as balance transfers and commit the resulting batch,
+    /// folding `withdrawals` into its exit-commitment.
+    pub fn commit_batch(&mut self, txs: Vec<SignedTx>, withdrawals: &[Withdrawal]) -> &Batch {
+        let pre_root = self.state.root();
+        for signed in &txs {
+            self.state.apply_transfer(
+                signed.tx.sender(),
+                signed.tx.recipient(),
+                signed.tx.value(),
+                signed.tx.nonce(),
+            );
+        }
+        let post_root = self.state.commit();
+
+        self.batches.push(Batch {
+            pre_root,
+            post_root,
+            txs,
+            exit_commitment: crate::bridge::exit_commitment(withdrawals),
+        });
+        self.batches.last().unwrap()
+    }
+}
+
+/// JSON-RPC error codes for the mempool admission checks below, in the
+/// implementation-defined server-error range reserved by the JSON-RPC 2.0
+/// spec (-32000 to -32099).
+const NONCE_TOO_LOW_CODE: i32 = -32000;
+const INSUFFICIENT_BALANCE_CODE: i32 = -32001;
+
+#[derive(Debug)]
+pub enum SchedulerError {
+    /// The nonce is not the account's next expected one: either lower (a
+    /// replay) or higher (out of order).
+    NonceTooLow { expected: U256, got: U256 },
+    InsufficientBalance { have: U256, need: U256 },
+}
+
+impl std::fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchedulerError::NonceTooLow { expected, got } => {
+                let relation = if got < expected { "too low" } else { "too high" };
+                write!(f, "nonce {relation}: expected {expected}, got {got}")
+            }
+            SchedulerError::InsufficientBalance { have, need } => {
+                write!(f, "insufficient balance: have {have}, need {need}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchedulerError {}
+
+impl From<SchedulerError> for RpcError {
+    fn from(err: SchedulerError) -> Self {
+        let code = match err {
+            SchedulerError::NonceTooLow { .. } => NONCE_TOO_LOW_CODE,
+            SchedulerError::InsufficientBalance { .. } => INSUFFICIENT_BALANCE_CODE,
+        };
+        RpcError::Call(CallError::Custom(ErrorObject::owned(
+            code,
+            err.to_string(),
+            None::<()>,
+        )))
+    }
+}
+
+/// Mempool admission control: tracks each account's known balance and next
+/// expected nonce so `submit_transaction` can reject replays,
+/// out-of-order txs and overdrafts before they ever reach a batch. Tracks
+/// each account's next expected withdrawal nonce separately, in its own
+/// counter, so a withdrawal request can't be replayed to debit an account
+/// more than once.
+#[derive(Default)]
+pub struct AccountScheduler {
+    accounts: HashMap<Address, (U256, U256)>,
+    withdrawal_nonces: HashMap<Address, U256>,
+}
+
+impl AccountScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Admit `tx`, staging its effect on `from`'s balance and nonce so that
+    /// subsequent submissions in the same batch are checked against it.
+    pub fn admit(&mut self, tx: &Tx) -> Result<(), SchedulerError> {
+        let (balance, next_nonce) = self.accounts.get(&tx.sender()).copied().unwrap_or_default();
+
+        if tx.nonce() != next_nonce {
+            return Err(SchedulerError::NonceTooLow {
+                expected: next_nonce,
+                got: tx.nonce(),
+            });
+        }
+        if balance < tx.value() {
+            return Err(SchedulerError::InsufficientBalance {
+                have: balance,
+                need: tx.value(),
+            });
+        }
+
+        self.accounts.insert(
+            tx.sender(),
+            (balance - tx.value(), next_nonce + U256::one()),
+        );
+        Ok(())
+    }
+
+    /// Credit `address`, e.g. when an L1 deposit lands. Does not touch the
+    /// account's nonce.
+    pub fn credit(&mut self, address: Address, value: U256) {
+        let entry = self.accounts.entry(address).or_default();
+        entry.0 = entry.0.saturating_add(value);
+    }
+
+    /// Admit a withdrawal request, staging the balance debit. `nonce` is
+    /// checked against this account's own withdrawal-nonce counter, separate
+    /// from the transfer nonce tracked in `accounts`, so the same signed
+    /// withdrawal request can never be replayed to debit twice.
+    pub fn debit(&mut self, address: Address, value: U256, nonce: U256) -> Result<(), SchedulerError> {
+        let next_withdrawal_nonce = self
+            .withdrawal_nonces
+            .get(&address)
+            .copied()
+            .unwrap_or_default();
+        if nonce != next_withdrawal_nonce {
+            return Err(SchedulerError::NonceTooLow {
+                expected: next_withdrawal_nonce,
+                got: nonce,
+            });
+        }
+
+        let (balance, transfer_nonce) = self.accounts.get(&address).copied().unwrap_or_default();
+        if balance < value {
+            return Err(SchedulerError::InsufficientBalance {
+                have: balance,
+                need: value,
+            });
+        }
+
+        self.accounts
+            .insert(address, (balance - value, transfer_nonce));
+        self.withdrawal_nonces
+            .insert(address, next_withdrawal_nonce + U256::one());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(byte: u8) -> Address {
+        Address::from_low_u64_be(byte as u64)
+    }
+
+    fn legacy_tx(from: Address, value: U256, nonce: U256) -> Tx {
+        Tx::Legacy(crate::LegacyTx {
+            from,
+            to: addr(0xff),
+            nonce,
+            value,
+        })
+    }
+
+    #[test]
+    fn admit_rejects_an_out_of_order_nonce() {
+        let mut scheduler = AccountScheduler::new();
+        let err = scheduler
+            .admit(&legacy_tx(addr(1), U256::zero(), U256::one()))
+            .unwrap_err();
+        assert!(matches!(err, SchedulerError::NonceTooLow { expected, got } if expected == U256::zero() && got == U256::one()));
+    }
+
+    #[test]
+    fn admit_rejects_insufficient_balance() {
+        let mut scheduler = AccountScheduler::new();
+        let err = scheduler
+            .admit(&legacy_tx(addr(1), U256::from(10), U256::zero()))
+            .unwrap_err();
+        assert!(
+            matches!(err, SchedulerError::InsufficientBalance { have, need } if have == U256::zero() && need == U256::from(10))
+        );
+    }
+
+    #[test]
+    fn credit_then_admit_spends_down_the_credited_balance() {
+        let mut scheduler = AccountScheduler::new();
+        scheduler.credit(addr(1), U256::from(100));
+
+        scheduler
+            .admit(&legacy_tx(addr(1), U256::from(40), U256::zero()))
+            .unwrap();
+        scheduler
+            .admit(&legacy_tx(addr(1), U256::from(40), U256::one()))
+            .unwrap();
+
+        let err = scheduler
+            .admit(&legacy_tx(addr(1), U256::from(40), U256::from(2)))
+            .unwrap_err();
+        assert!(matches!(err, SchedulerError::InsufficientBalance { .. }));
+    }
+
+    #[test]
+    fn credit_saturates_instead_of_panicking_on_overflow() {
+        let mut scheduler = AccountScheduler::new();
+        scheduler.credit(addr(1), U256::MAX);
+        scheduler.credit(addr(1), U256::from(10));
+        assert_eq!(scheduler.accounts[&addr(1)].0, U256::MAX);
+    }
+
+    #[test]
+    fn debit_rejects_insufficient_balance_and_leaves_balance_unchanged() {
+        let mut scheduler = AccountScheduler::new();
+        scheduler.credit(addr(1), U256::from(5));
+
+        let err = scheduler
+            .debit(addr(1), U256::from(10), U256::zero())
+            .unwrap_err();
+        assert!(matches!(err, SchedulerError::InsufficientBalance { .. }));
+        assert_eq!(scheduler.accounts[&addr(1)].0, U256::from(5));
+    }
+
+    #[test]
+    fn debit_does_not_consume_a_transfer_nonce_slot() {
+        let mut scheduler = AccountScheduler::new();
+        scheduler.credit(addr(1), U256::from(100));
+        scheduler
+            .admit(&legacy_tx(addr(1), U256::from(10), U256::zero()))
+            .unwrap();
+
+        scheduler.debit(addr(1), U256::from(10), U256::zero()).unwrap();
+
+        // The next tx still expects transfer nonce 1: debit didn't bump it.
+        scheduler
+            .admit(&legacy_tx(addr(1), U256::from(10), U256::one()))
+            .unwrap();
+    }
+
+    #[test]
+    fn debit_rejects_a_replayed_withdrawal_nonce() {
+        let mut scheduler = AccountScheduler::new();
+        scheduler.credit(addr(1), U256::from(100));
+
+        scheduler.debit(addr(1), U256::from(10), U256::zero()).unwrap();
+
+        let err = scheduler
+            .debit(addr(1), U256::from(10), U256::zero())
+            .unwrap_err();
+        assert!(matches!(err, SchedulerError::NonceTooLow { .. }));
+        // The replay was rejected before it could touch the balance again.
+        assert_eq!(scheduler.accounts[&addr(1)].0, U256::from(90));
+    }
+}
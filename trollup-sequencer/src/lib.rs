@@ -0,0 +1,3 @@
+pub mod merkle_tree;
+pub mod poseidon_hasher;
+pub mod state;
@@ -0,0 +1,176 @@
+use std::collections::{BTreeSet, HashMap};
+use std::marker::PhantomData;
+
+use ethers::types::U256;
+
+/// A binary hash function over two `U256` limbs, used to combine a node's
+/// children into its parent hash. `PoseidonHasher` is the concrete
+/// implementation used by the rollup state tree.
+pub trait Hasher {
+    fn write_h256(&mut self, w: &U256);
+    fn finish(self) -> U256;
+}
+
+fn hash_pair<H: Hasher + Default>(left: U256, right: U256) -> U256 {
+    let mut hasher = H::default();
+    hasher.write_h256(&left);
+    hasher.write_h256(&right);
+    hasher.finish()
+}
+
+/// A sparse Merkle tree of fixed `depth`, addressed by `U256` leaf index.
+///
+/// Subtrees that have never been touched are never materialized: the hash
+/// of an empty subtree at each height is precomputed once in `new`, so
+/// looking up an absent node is an O(1) table lookup rather than a walk
+/// down a tree of zeroes.
+pub struct SparseMerkleTree<H> {
+    depth: usize,
+    empty_hashes: Vec<U256>,
+    leaves: HashMap<U256, U256>,
+    nodes: HashMap<(usize, U256), U256>,
+    _hasher: PhantomData<H>,
+}
+
+impl<H: Hasher + Default> SparseMerkleTree<H> {
+    pub fn new(depth: usize) -> Self {
+        let mut empty_hashes = Vec::with_capacity(depth + 1);
+        empty_hashes.push(U256::zero());
+        for level in 1..=depth {
+            let child = empty_hashes[level - 1];
+            empty_hashes.push(hash_pair::<H>(child, child));
+        }
+        Self {
+            depth,
+            empty_hashes,
+            leaves: HashMap::new(),
+            nodes: HashMap::new(),
+            _hasher: PhantomData,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn root(&self) -> U256 {
+        self.node(self.depth, U256::zero())
+    }
+
+    fn node(&self, level: usize, index: U256) -> U256 {
+        if level == 0 {
+            return *self.leaves.get(&index).unwrap_or(&self.empty_hashes[0]);
+        }
+        *self.nodes.get(&(level, index)).unwrap_or(&self.empty_hashes[level])
+    }
+
+    /// Set a single leaf and recompute its path to the root.
+    pub fn update_leaf(&mut self, index: U256, leaf_hash: U256) -> U256 {
+        self.apply_batch([(index, leaf_hash)])
+    }
+
+    /// Apply a batch of leaf updates, recomputing only the ancestor paths of
+    /// the leaves that actually changed: dirty indices are collected level
+    /// by level, hashing each dirty node against its sibling, and the set of
+    /// dirty parents shrinks (often collapsing, when siblings are both
+    /// dirty) as it climbs towards the root.
+    pub fn apply_batch(&mut self, updates: impl IntoIterator<Item = (U256, U256)>) -> U256 {
+        let mut dirty = BTreeSet::new();
+        for (index, leaf_hash) in updates {
+            self.leaves.insert(index, leaf_hash);
+            dirty.insert(index);
+        }
+
+        for level in 0..self.depth {
+            let mut parents = BTreeSet::new();
+            for index in &dirty {
+                let sibling = index ^ U256::one();
+                let (left, right) = if index % 2 == U256::zero() {
+                    (self.node(level, *index), self.node(level, sibling))
+                } else {
+                    (self.node(level, sibling), self.node(level, *index))
+                };
+                let parent = index / 2;
+                self.nodes.insert((level + 1, parent), hash_pair::<H>(left, right));
+                parents.insert(parent);
+            }
+            dirty = parents;
+        }
+
+        self.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trivial hasher for tests: not collision-resistant, just enough to
+    /// tell two different inputs apart so tree-shape assertions don't
+    /// depend on any real hash function's internals.
+    #[derive(Default)]
+    struct SumHasher(U256);
+
+    impl Hasher for SumHasher {
+        fn write_h256(&mut self, w: &U256) {
+            self.0 = self.0.overflowing_mul(U256::from(31)).0 + w + U256::one();
+        }
+
+        fn finish(self) -> U256 {
+            self.0
+        }
+    }
+
+    type TestTree = SparseMerkleTree<SumHasher>;
+
+    #[test]
+    fn empty_tree_root_matches_precomputed_empty_hash() {
+        let tree = TestTree::new(4);
+        assert_eq!(tree.root(), tree.empty_hashes[4]);
+    }
+
+    #[test]
+    fn update_leaf_changes_the_root() {
+        let mut tree = TestTree::new(4);
+        let empty_root = tree.root();
+        tree.update_leaf(U256::from(3), U256::from(42));
+        assert_ne!(tree.root(), empty_root);
+    }
+
+    #[test]
+    fn updating_an_untouched_leaf_back_to_empty_restores_the_root() {
+        let mut tree = TestTree::new(4);
+        let empty_root = tree.root();
+        tree.update_leaf(U256::from(3), U256::from(42));
+        tree.update_leaf(U256::from(3), U256::zero());
+        assert_eq!(tree.root(), empty_root);
+    }
+
+    #[test]
+    fn apply_batch_matches_sequential_update_leaf_calls() {
+        let mut batched = TestTree::new(4);
+        batched.apply_batch([
+            (U256::from(1), U256::from(10)),
+            (U256::from(5), U256::from(20)),
+            (U256::from(6), U256::from(30)),
+        ]);
+
+        let mut sequential = TestTree::new(4);
+        sequential.update_leaf(U256::from(1), U256::from(10));
+        sequential.update_leaf(U256::from(5), U256::from(20));
+        sequential.update_leaf(U256::from(6), U256::from(30));
+
+        assert_eq!(batched.root(), sequential.root());
+    }
+
+    #[test]
+    fn distinct_leaves_produce_distinct_roots() {
+        let mut a = TestTree::new(4);
+        a.update_leaf(U256::from(7), U256::from(1));
+
+        let mut b = TestTree::new(4);
+        b.update_leaf(U256::from(7), U256::from(2));
+
+        assert_ne!(a.root(), b.root());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+
+use ethers::types::{Address, U256};
+
+use crate::merkle_tree::{Hasher, SparseMerkleTree};
+use crate::poseidon_hasher::PoseidonHasher;
+
+/// Depth of the account state tree. 2^256 leaves is enough to key every
+/// possible `Address` directly, with no collision handling needed.
+pub const TREE_DEPTH: usize = 256;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: U256,
+}
+
+impl Account {
+    fn leaf_hash(&self) -> U256 {
+        let mut hasher = PoseidonHasher::default();
+        hasher.write_h256(&self.balance);
+        hasher.write_h256(&self.nonce);
+        hasher.finish()
+    }
+}
+
+fn leaf_index(address: &Address) -> U256 {
+    U256::from_big_endian(address.as_bytes())
+}
+
+/// The rollup's account-balance state, backed by a sparse Merkle tree of
+/// `Poseidon(balance || nonce)` leaves keyed by `Address`.
+///
+/// Transfers mutate the in-memory account map immediately but only touch
+/// the tree once `commit` is called, so a whole batch of transfers costs a
+/// single tree walk instead of one per transfer.
+pub struct StateTree {
+    tree: SparseMerkleTree<PoseidonHasher>,
+    accounts: HashMap<Address, Account>,
+    dirty: HashSet<Address>,
+}
+
+impl StateTree {
+    pub fn new() -> Self {
+        Self {
+            tree: SparseMerkleTree::new(TREE_DEPTH),
+            accounts: HashMap::new(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    pub fn root(&self) -> U256 {
+        self.tree.root()
+    }
+
+    pub fn account(&self, address: &Address) -> Account {
+        self.accounts.get(address).copied().unwrap_or_default()
+    }
+
+    /// Debit `from`, credit `to` and bump `from`'s nonce. Callers are
+    /// expected to have already admitted the transfer (see the account
+    /// scheduler), so this applies it unconditionally.
+    pub fn apply_transfer(&mut self, from: Address, to: Address, value: U256, nonce: U256) {
+        let mut sender = self.account(&from);
+        sender.balance = sender.balance.saturating_sub(value);
+        sender.nonce = nonce + U256::one();
+        self.accounts.insert(from, sender);
+        self.dirty.insert(from);
+
+        let mut recipient = self.account(&to);
+        recipient.balance = recipient.balance.saturating_add(value);
+        self.accounts.insert(to, recipient);
+        self.dirty.insert(to);
+    }
+
+    /// Credit `to` with `value` out of thin air, e.g. for an L1 deposit.
+    pub fn credit(&mut self, to: Address, value: U256) {
+        let mut account = self.account(&to);
+        account.balance = account.balance.saturating_add(value);
+        self.accounts.insert(to, account);
+        self.dirty.insert(to);
+    }
+
+    /// Debit `from` by `value`, e.g. for a withdrawal. Callers are expected
+    /// to have already checked `from` can cover `value` (see the account
+    /// scheduler), so this applies it unconditionally.
+    pub fn debit(&mut self, from: Address, value: U256) {
+        let mut account = self.account(&from);
+        account.balance = account.balance.saturating_sub(value);
+        self.accounts.insert(from, account);
+        self.dirty.insert(from);
+    }
+
+    /// Flush every account touched since the last commit into the Merkle
+    /// tree in a single batch and return the new state root.
+    pub fn commit(&mut self) -> U256 {
+        let updates: Vec<(U256, U256)> = self
+            .dirty
+            .drain()
+            .map(|address| (leaf_index(&address), self.accounts[&address].leaf_hash()))
+            .collect();
+        self.tree.apply_batch(updates)
+    }
+}
+
+impl Default for StateTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(byte: u8) -> Address {
+        Address::from_low_u64_be(byte as u64)
+    }
+
+    #[test]
+    fn apply_transfer_debits_sender_and_credits_recipient() {
+        let mut state = StateTree::new();
+        state.credit(addr(1), U256::from(100));
+        state.commit();
+
+        state.apply_transfer(addr(1), addr(2), U256::from(40), U256::zero());
+
+        assert_eq!(state.account(&addr(1)).balance, U256::from(60));
+        assert_eq!(state.account(&addr(2)).balance, U256::from(40));
+    }
+
+    #[test]
+    fn apply_transfer_bumps_the_sender_nonce() {
+        let mut state = StateTree::new();
+        state.apply_transfer(addr(1), addr(2), U256::zero(), U256::from(5));
+        assert_eq!(state.account(&addr(1)).nonce, U256::from(6));
+    }
+
+    #[test]
+    fn apply_transfer_saturates_rather_than_panicking_on_overflow() {
+        let mut state = StateTree::new();
+        state.credit(addr(2), U256::MAX);
+
+        state.apply_transfer(addr(1), addr(2), U256::from(10), U256::zero());
+
+        assert_eq!(state.account(&addr(2)).balance, U256::MAX);
+    }
+
+    #[test]
+    fn debit_saturates_instead_of_underflowing() {
+        let mut state = StateTree::new();
+        state.debit(addr(1), U256::from(10));
+        assert_eq!(state.account(&addr(1)).balance, U256::zero());
+    }
+
+    #[test]
+    fn commit_changes_the_root_only_for_dirty_accounts() {
+        let mut state = StateTree::new();
+        let empty_root = state.commit();
+
+        state.credit(addr(1), U256::from(5));
+        let root_after_credit = state.commit();
+        assert_ne!(root_after_credit, empty_root);
+
+        // Nothing touched since the last commit: the root is stable.
+        assert_eq!(state.commit(), root_after_credit);
+    }
+}